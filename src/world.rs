@@ -1,317 +1,1005 @@
-use rand::Rng;
-
-type Point = (usize, usize);
-
-#[derive(Clone)]
-pub enum TileType {
-    Empty,
-    Wall,
-    Corridor,
-    Floor,
-}
-
-enum CorridorType {
-    Horizontal,
-    Vertical
-}
-
-trait Tileable {
-    fn tile(&self, grid: &mut TileGrid) -> Result<(), String>;
-}
-
-const LEFT: (i8, i8) = (-1i8, 0);
-const RIGHT: (i8, i8) = (1i8, 0);
-const UP: (i8, i8) = (0, -1i8);
-const DOWN: (i8, i8) = (0, 1i8);
-
-struct RoomEdge {
-    start: Point,
-    mid_point: Point,
-    end: Point,
-    corridor_dir: (i8, i8)
-}
-
-impl RoomEdge {
-    pub fn new(start: Point, end: Point, corridor_dir: (i8, i8)) -> RoomEdge {
-        RoomEdge {
-            start,
-            end,
-            mid_point: (end.0 - start.0 / 2, end.1 - start.1 / 2),
-            corridor_dir
-        }
-    }
-}
-
-struct Room {
-    start: Point,
-    center: Point,
-    width: usize,
-    height: usize,
-    edges: [RoomEdge; 4]
-}
-
-impl Room {
-    fn new(start: Point, width: usize, height: usize) -> Room {
-        Room {
-            start,
-            width,
-            height,
-            center: (start.0 + width / 2, start.1 + height / 2),
-            edges: [
-                RoomEdge::new(start, (start.0 + width, start.1), UP),
-                RoomEdge::new(start, (start.0, start.1 + height), LEFT),
-                RoomEdge::new((start.0, start.1 + height), (start.0 + width, start.1 + height), DOWN),
-                RoomEdge::new((start.0 + width, start.1), (start.0 + width, start.1), RIGHT)
-            ]
-        }
-    }
-}
-
-impl Tileable for Room {
-    fn tile(&self, grid: &mut TileGrid) -> Result<(), String> {
-        // TODO: Detect if the room would leave the grid.
-        let endx = self.start.0 + self.width;
-        let endy = self.start.1 + self.height;
-
-        // Set the walls
-        for x in self.start.0..(endx + 1) {
-            grid.set_empty_tile(x, self.start.1, TileType::Wall);
-            grid.set_empty_tile(x, endy, TileType::Wall);
-        }
-
-        for y in self.start.1..endy {
-            grid.set_empty_tile(self.start.0, y, TileType::Wall);
-            grid.set_empty_tile(endx, y, TileType::Wall);
-        }
-
-        // Fill the room
-        for x in (self.start.0 + 1)..endx {
-            for y in (self.start.1 + 1)..endy {
-                grid.set_tile(x, y, TileType::Floor);
-            }
-        }
-
-        Ok(())
-    }
-}
-
-struct Corridor {
-    start: Point,
-    length: usize,
-    direction: CorridorType
-}
-
-impl Corridor {
-    fn new(start: Point, length: usize, direction: CorridorType) -> Corridor {
-        Corridor {
-            start,
-            length,
-            direction
-        }
-    }
-
-    fn tile_vertical(&self, grid: &mut TileGrid) {
-        let x = self.start.0;
-        let endy = self.start.1 + self.length;
-        for y in self.start.1..endy {
-            grid.set_empty_tile(x - 1, y, TileType::Wall);
-            grid.set_tile(x, y, TileType::Floor);
-            grid.set_empty_tile(x + 1, y, TileType::Wall);
-        }
-    }
-
-    fn tile_horizontal(&self, grid: &mut TileGrid) {
-        let y = self.start.1;
-        let endx = self.start.0 + self.length;
-        for x in self.start.0..endx {
-            grid.set_empty_tile(x, y - 1, TileType::Wall);
-            grid.set_tile(x, y, TileType::Floor);
-            grid.set_empty_tile(x, y - 1, TileType::Wall);
-        }
-    }
-}
-
-impl Tileable for Corridor {
-    fn tile(&self, grid: &mut TileGrid) -> Result<(), String> {
-        // TODO: ensure the corridor isn't leaving the grid.
-        match self.direction {
-            CorridorType::Horizontal => self.tile_horizontal(grid),
-            CorridorType::Vertical => self.tile_vertical(grid)
-        }
-        Ok(())
-    }
-}
-
-pub struct TileGrid {
-    grid: Vec<Vec<TileType>>
-}
-
-impl<'a> TileGrid {
-    pub fn new(size: usize) -> TileGrid {
-        let mut grid = TileGrid {
-            grid: Vec::with_capacity(size)
-        };
-
-        for _ in 0..size {
-            let mut subvec = Vec::with_capacity(size);
-            for _ in 0..size {
-                subvec.push(TileType::Empty);
-            }
-            grid.grid.push(subvec);
-        }
-
-        return grid;
-    }
-
-    fn set_tile(&mut self, x: usize, y: usize, tile: TileType) {
-        self.grid[y][x] = tile;
-    }
-
-    /// Sets a tile if nothing lies underneath it.
-    fn set_empty_tile(&mut self, x: usize, y: usize, tile: TileType) {
-        self.set_tile(x, y, match self.grid[y][x] {
-            TileType::Empty => tile,
-            _ => self.grid[y][x].clone()
-        })
-    }
-
-    pub fn raw_data(&'a self) -> &'a Vec<Vec<TileType>> {
-        &self.grid
-    }
-}
-
-pub struct World {
-    size: usize,
-    rooms: Vec<Room>,
-    corridors: Vec<Corridor>
-}
-
-pub trait GameWorld {
-    fn new(size: usize) -> Self;
-
-    fn generate(&mut self);
-
-    fn to_tilegrid(&self) -> TileGrid;
-}
-
-fn hor_dist(point1: Point, point2: Point) -> f32 {
-    point2.0 as f32 - point1.0 as f32
-}
-
-fn ver_dist(point1: Point, point2: Point) -> f32 {
-    point2.1 as f32 - point1.1 as f32
-}
-
-/// The distance between 2 points
-fn distance(point1: Point, point2: Point) -> f32 {
-    (
-        hor_dist(point1, point2).powf(2.0)
-        +
-        ver_dist(point1, point2).powf(2.0)
-    ).sqrt()
-}
-
-impl World {
-    fn overlaps(&self, start: Point, width: usize, height: usize, padding: usize) -> bool {
-        for room in &self.rooms {
-            if room.start.0 < start.0 + width + padding &&
-                room.start.0 + room.width + padding > start.0 &&
-                room.start.1 < start.1 + height + padding &&
-                room.start.1 + room.height + padding > start.1 {
-                return true;
-            }
-        }
-
-        return false;
-    }
-
-    fn room_distances(&self, point: Point) -> Vec<(usize, f32)> {
-        let mut dists: Vec<(usize, f32)> = self.rooms
-            .iter()
-            .enumerate()
-            .map(|(room_num, room): (usize, &Room)| -> (usize, f32) {
-                (room_num, distance(point, room.center))
-            })
-            .collect();
-        dists.sort_by(|(_, dista): &(usize, f32), (_, distb): &(usize, f32)| dista.partial_cmp(&distb).unwrap());
-        dists
-    }
-
-    fn random_room(&self) -> Result<Room, String> {
-        // TODO: Detect when not enough space is left to allocate a room.
-        let mut rng = rand::thread_rng();
-        let room_width = rng.gen_range(3, 6);
-        let room_height = rng.gen_range(3, 6);
-
-        // TODO: Find a way to write a lambda to generate the start point.
-        let mut start: Point = (
-            rng.gen_range(0, self.size - room_width),
-            rng.gen_range(0, self.size - room_height)
-        );
-
-        while self.overlaps(start, room_width, room_height, 2) {
-            start = (
-                rng.gen_range(0, self.size - room_width),
-                rng.gen_range(0, self.size - room_height)
-            );
-        }
-
-        Ok(Room::new(start, room_width, room_height))
-    }
-}
-
-impl GameWorld for World {
-    fn new(size: usize) -> World {
-        World {
-            size,
-            rooms: Vec::new(),
-            corridors: Vec::new()
-        }
-    }
-
-    fn generate(&mut self) {
-        let mut rng = rand::thread_rng();
-        let room_number = rng.gen_range(3, 5);
-
-        for _ in 0..room_number {
-            self.rooms.push(self.random_room().unwrap());
-        }
-
-        for room in &self.rooms {
-            // Find the nearest room.
-            let distances = self.room_distances(room.center);
-            let nearest_room = &self.rooms[distances[1].0];
-
-            self.corridors.push(Corridor::new(
-                room.center,
-                hor_dist(room.center, nearest_room.center) as usize,
-                CorridorType::Horizontal
-            ));
-        }
-    }
-
-    fn to_tilegrid(&self) -> TileGrid {
-        let mut grid = TileGrid::new(self.size);
-
-        for room in &self.rooms {
-            room.tile(&mut grid).unwrap();
-        }
-
-        for corridor in &self.corridors {
-            // todo
-        }
-
-        grid
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_generates_world() {
-        let mut world = World::new(128);
-        world.generate();
-    }
-}
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+pub type Point = (usize, usize);
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TileType {
+    Empty,
+    Wall,
+    Corridor,
+    Floor,
+}
+
+trait Tileable {
+    fn tile(&self, grid: &mut TileGrid) -> Result<(), String>;
+}
+
+const LEFT: (i8, i8) = (-1i8, 0);
+const RIGHT: (i8, i8) = (1i8, 0);
+const UP: (i8, i8) = (0, -1i8);
+const DOWN: (i8, i8) = (0, 1i8);
+
+struct RoomEdge {
+    start: Point,
+    mid_point: Point,
+    end: Point,
+    corridor_dir: (i8, i8)
+}
+
+impl RoomEdge {
+    pub fn new(start: Point, end: Point, corridor_dir: (i8, i8)) -> RoomEdge {
+        RoomEdge {
+            start,
+            end,
+            mid_point: (end.0 - start.0 / 2, end.1 - start.1 / 2),
+            corridor_dir
+        }
+    }
+}
+
+pub struct Room {
+    start: Point,
+    center: Point,
+    width: usize,
+    height: usize,
+    edges: [RoomEdge; 4]
+}
+
+impl Room {
+    fn new(start: Point, width: usize, height: usize) -> Room {
+        Room {
+            start,
+            width,
+            height,
+            center: (start.0 + width / 2, start.1 + height / 2),
+            edges: [
+                RoomEdge::new(start, (start.0 + width, start.1), UP),
+                RoomEdge::new(start, (start.0, start.1 + height), LEFT),
+                RoomEdge::new((start.0, start.1 + height), (start.0 + width, start.1 + height), DOWN),
+                RoomEdge::new((start.0 + width, start.1), (start.0 + width, start.1), RIGHT)
+            ]
+        }
+    }
+
+    /// Top-left corner of the room.
+    pub fn start(&self) -> Point {
+        self.start
+    }
+
+    /// Point roughly in the middle of the room's floor.
+    pub fn center(&self) -> Point {
+        self.center
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Tileable for Room {
+    fn tile(&self, grid: &mut TileGrid) -> Result<(), String> {
+        // TODO: Detect if the room would leave the grid.
+        let endx = self.start.0 + self.width;
+        let endy = self.start.1 + self.height;
+
+        // Set the walls
+        for x in self.start.0..(endx + 1) {
+            grid.set_empty_tile(x, self.start.1, TileType::Wall);
+            grid.set_empty_tile(x, endy, TileType::Wall);
+        }
+
+        for y in self.start.1..endy {
+            grid.set_empty_tile(self.start.0, y, TileType::Wall);
+            grid.set_empty_tile(endx, y, TileType::Wall);
+        }
+
+        // Fill the room
+        for x in (self.start.0 + 1)..endx {
+            for y in (self.start.1 + 1)..endy {
+                grid.set_tile(x, y, TileType::Floor);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of neighboring walls (out of 8) at which a cellular-automata cave
+/// cell becomes a wall itself.
+const CAVE_WALL_THRESHOLD: usize = 5;
+
+#[derive(Clone)]
+pub struct TileGrid {
+    grid: Vec<Vec<TileType>>
+}
+
+impl<'a> TileGrid {
+    pub fn new(size: usize) -> TileGrid {
+        let mut grid = TileGrid {
+            grid: Vec::with_capacity(size)
+        };
+
+        for _ in 0..size {
+            let mut subvec = Vec::with_capacity(size);
+            for _ in 0..size {
+                subvec.push(TileType::Empty);
+            }
+            grid.grid.push(subvec);
+        }
+
+        return grid;
+    }
+
+    fn set_tile(&mut self, x: usize, y: usize, tile: TileType) {
+        self.grid[y][x] = tile;
+    }
+
+    fn get(&self, x: usize, y: usize) -> &TileType {
+        &self.grid[y][x]
+    }
+
+    /// Sets a tile if nothing lies underneath it.
+    fn set_empty_tile(&mut self, x: usize, y: usize, tile: TileType) {
+        self.set_tile(x, y, match self.grid[y][x] {
+            TileType::Empty => tile,
+            _ => self.grid[y][x].clone()
+        })
+    }
+
+    pub fn raw_data(&'a self) -> &'a Vec<Vec<TileType>> {
+        &self.grid
+    }
+
+    /// Randomly fills every cell as `Wall` or `Floor`, with `wall_percent`
+    /// (0-100) controlling the initial wall density.
+    fn fill_random(&mut self, wall_percent: u32, rng: &mut StdRng) {
+        let size = self.grid.len();
+
+        for y in 0..size {
+            for x in 0..size {
+                let tile = if rng.gen_range(0, 100) < wall_percent {
+                    TileType::Wall
+                } else {
+                    TileType::Floor
+                };
+                self.set_tile(x, y, tile);
+            }
+        }
+    }
+
+    /// Counts how many of the 8 neighbors of `(x, y)` are walls, treating
+    /// out-of-bounds neighbors as walls.
+    fn wall_neighbor_count(&self, x: usize, y: usize) -> usize {
+        let size = self.grid.len();
+        let mut count = 0;
+
+        for dy in -1i32..=1 {
+            for dx in -1i32..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+
+                let is_wall = if nx < 0 || ny < 0 || nx as usize >= size || ny as usize >= size {
+                    true
+                } else {
+                    matches!(self.get(nx as usize, ny as usize), TileType::Wall)
+                };
+
+                if is_wall {
+                    count += 1;
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Runs one cellular-automata smoothing pass: a cell becomes `Wall` if
+    /// `CAVE_WALL_THRESHOLD`+ of its neighbors are walls, `Floor` otherwise.
+    fn smooth(&mut self) {
+        let size = self.grid.len();
+        let mut next = self.clone();
+
+        for y in 0..size {
+            for x in 0..size {
+                let tile = if self.wall_neighbor_count(x, y) >= CAVE_WALL_THRESHOLD {
+                    TileType::Wall
+                } else {
+                    TileType::Floor
+                };
+                next.set_tile(x, y, tile);
+            }
+        }
+
+        *self = next;
+    }
+
+    fn first_floor(&self) -> Option<Point> {
+        let size = self.grid.len();
+
+        for y in 0..size {
+            for x in 0..size {
+                if let TileType::Floor = self.get(x, y) {
+                    return Some((x, y));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Flood-fills reachable floor/corridor tiles from `start`, then walls
+    /// off every `Floor`/`Corridor` tile that wasn't reached, so no player
+    /// can be placed in (or wander into) a sealed-off pocket.
+    fn cull_unreachable(&mut self, start: Point) {
+        use std::collections::VecDeque;
+
+        let size = self.grid.len();
+        let mut reached = vec![vec![false; size]; size];
+        let mut frontier = VecDeque::new();
+
+        if let TileType::Floor | TileType::Corridor = self.get(start.0, start.1) {
+            reached[start.1][start.0] = true;
+            frontier.push_back(start);
+        }
+
+        while let Some((x, y)) = frontier.pop_front() {
+            for &(dx, dy) in &[LEFT, RIGHT, UP, DOWN] {
+                let nx = x as isize + dx as isize;
+                let ny = y as isize + dy as isize;
+
+                if nx < 0 || ny < 0 || nx as usize >= size || ny as usize >= size {
+                    continue;
+                }
+
+                let next = (nx as usize, ny as usize);
+                if reached[next.1][next.0] {
+                    continue;
+                }
+
+                if let TileType::Floor | TileType::Corridor = self.get(next.0, next.1) {
+                    reached[next.1][next.0] = true;
+                    frontier.push_back(next);
+                }
+            }
+        }
+
+        for (y, row) in reached.iter().enumerate() {
+            for (x, &tile_reached) in row.iter().enumerate() {
+                let walkable = matches!(self.get(x, y), TileType::Floor | TileType::Corridor);
+
+                if walkable && !tile_reached {
+                    self.set_tile(x, y, TileType::Wall);
+                }
+            }
+        }
+    }
+}
+
+/// Shared state threaded through a `World`'s `MapFilter` pipeline: the rooms
+/// placed so far and the spawn point, once a filter has picked one.
+pub struct BuildData {
+    pub rooms: Vec<Room>,
+    pub spawn: Option<Point>
+}
+
+impl BuildData {
+    fn new() -> BuildData {
+        BuildData {
+            rooms: Vec::new(),
+            spawn: None
+        }
+    }
+}
+
+/// A single generation stage in a `World`'s pipeline: mutates the shared
+/// `TileGrid` and/or `BuildData` in place.
+pub trait MapFilter {
+    fn apply(&self, grid: &mut TileGrid, data: &mut BuildData, rng: &mut StdRng);
+}
+
+/// Smallest side a BSP leaf may have before it can no longer be split.
+const BSP_MIN_SIZE: usize = 8;
+
+/// A leaf rectangle in the BSP tree. Leaves that are still big enough get
+/// split in two; the rest each host exactly one `Room`.
+struct BspLeaf {
+    start: Point,
+    width: usize,
+    height: usize
+}
+
+impl BspLeaf {
+    /// Splits along a random axis, provided both halves stay at least
+    /// `BSP_MIN_SIZE` wide/tall. Prefers splitting the longer axis when
+    /// only one is viable.
+    fn split(&self, rng: &mut StdRng) -> Option<(BspLeaf, BspLeaf)> {
+        let can_split_horizontally = self.height >= BSP_MIN_SIZE * 2;
+        let can_split_vertically = self.width >= BSP_MIN_SIZE * 2;
+
+        if !can_split_horizontally && !can_split_vertically {
+            return None;
+        }
+
+        let split_horizontally = if can_split_horizontally && can_split_vertically {
+            rng.gen_range(0, 2) == 0
+        } else {
+            can_split_horizontally
+        };
+
+        Some(if split_horizontally {
+            let split_at = rng.gen_range(BSP_MIN_SIZE, self.height - BSP_MIN_SIZE + 1);
+            (
+                BspLeaf { start: self.start, width: self.width, height: split_at },
+                BspLeaf {
+                    start: (self.start.0, self.start.1 + split_at),
+                    width: self.width,
+                    height: self.height - split_at
+                }
+            )
+        } else {
+            let split_at = rng.gen_range(BSP_MIN_SIZE, self.width - BSP_MIN_SIZE + 1);
+            (
+                BspLeaf { start: self.start, width: split_at, height: self.height },
+                BspLeaf {
+                    start: (self.start.0 + split_at, self.start.1),
+                    width: self.width - split_at,
+                    height: self.height
+                }
+            )
+        })
+    }
+}
+
+/// Recursively splits the largest leaf rectangle until `splits` cuts have
+/// been made (or the largest leaf is too small to split further).
+fn bsp_leaves(size: usize, rng: &mut StdRng) -> Vec<BspLeaf> {
+    let mut leaves = vec![BspLeaf { start: (0, 0), width: size, height: size }];
+    let splits = rng.gen_range(3, 6);
+
+    for _ in 0..splits {
+        let largest = leaves
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, leaf)| leaf.width * leaf.height)
+            .map(|(index, _)| index);
+
+        let index = match largest {
+            Some(index) => index,
+            None => break
+        };
+
+        match leaves[index].split(rng) {
+            Some((left, right)) => {
+                leaves.splice(index..index + 1, vec![left, right]);
+            },
+            None => break
+        }
+    }
+
+    leaves
+}
+
+fn room_in_leaf(leaf: &BspLeaf, rng: &mut StdRng) -> Room {
+    let padding = rng.gen_range(1, 3);
+    let start = (leaf.start.0 + padding, leaf.start.1 + padding);
+    let width = leaf.width - padding * 2;
+    let height = leaf.height - padding * 2;
+
+    Room::new(start, width, height)
+}
+
+fn overlaps(start: Point, width: usize, height: usize, padding: usize, rooms: &[Room]) -> bool {
+    for room in rooms {
+        if room.start.0 < start.0 + width + padding &&
+            room.start.0 + room.width + padding > start.0 &&
+            room.start.1 < start.1 + height + padding &&
+            room.start.1 + room.height + padding > start.1 {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+fn room_distances(point: Point, rooms: &[Room]) -> Vec<(usize, f32)> {
+    let mut dists: Vec<(usize, f32)> = rooms
+        .iter()
+        .enumerate()
+        .map(|(room_num, room): (usize, &Room)| -> (usize, f32) {
+            (room_num, distance(point, room.center))
+        })
+        .collect();
+    dists.sort_by(|(_, dista): &(usize, f32), (_, distb): &(usize, f32)| dista.partial_cmp(&distb).unwrap());
+    dists
+}
+
+fn random_room(grid: &TileGrid, existing: &[Room], rng: &mut StdRng) -> Result<Room, String> {
+    // TODO: Detect when not enough space is left to allocate a room.
+    let size = grid.raw_data().len();
+    let room_width = rng.gen_range(3, 6);
+    let room_height = rng.gen_range(3, 6);
+
+    // TODO: Find a way to write a lambda to generate the start point.
+    let mut start: Point = (
+        rng.gen_range(0, size - room_width),
+        rng.gen_range(0, size - room_height)
+    );
+
+    while overlaps(start, room_width, room_height, 2, existing) {
+        start = (
+            rng.gen_range(0, size - room_width),
+            rng.gen_range(0, size - room_height)
+        );
+    }
+
+    Ok(Room::new(start, room_width, room_height))
+}
+
+fn hor_dist(point1: Point, point2: Point) -> f32 {
+    point2.0 as f32 - point1.0 as f32
+}
+
+fn ver_dist(point1: Point, point2: Point) -> f32 {
+    point2.1 as f32 - point1.1 as f32
+}
+
+/// The distance between 2 points
+fn distance(point1: Point, point2: Point) -> f32 {
+    (
+        hor_dist(point1, point2).powf(2.0)
+        +
+        ver_dist(point1, point2).powf(2.0)
+    ).sqrt()
+}
+
+fn manhattan_dist(point1: Point, point2: Point) -> u32 {
+    (hor_dist(point1, point2).abs() + ver_dist(point1, point2).abs()) as u32
+}
+
+/// The cost of stepping onto `tile`: cheap on existing floor/corridor so
+/// paths prefer to merge into what's already carved, expensive through
+/// untouched rock, plus a small jitter so straight hallways get discouraged.
+fn step_cost(tile: &TileType, rng: &mut StdRng) -> u32 {
+    let base = match tile {
+        TileType::Floor | TileType::Corridor => 1,
+        TileType::Empty | TileType::Wall => 10
+    };
+
+    base + rng.gen_range(0, 4)
+}
+
+/// A* a walkable path between `start` and `end` over `grid`, returning the
+/// tiles to mark `Corridor`. Produces an L-shaped or wandering route rather
+/// than a single straight run, and is cheap to reuse existing floor.
+fn a_star_path(grid: &TileGrid, start: Point, end: Point, rng: &mut StdRng) -> Vec<Point> {
+    use std::cmp::Reverse;
+    use std::collections::{BinaryHeap, HashMap};
+
+    let size = grid.raw_data().len();
+    let directions = [LEFT, RIGHT, UP, DOWN];
+
+    let mut frontier = BinaryHeap::new();
+    frontier.push(Reverse((0u32, start)));
+
+    let mut came_from: HashMap<Point, Point> = HashMap::new();
+    let mut cost_so_far: HashMap<Point, u32> = HashMap::new();
+    cost_so_far.insert(start, 0);
+
+    while let Some(Reverse((_, current))) = frontier.pop() {
+        if current == end {
+            break;
+        }
+
+        for &(dx, dy) in &directions {
+            let nx = current.0 as isize + dx as isize;
+            let ny = current.1 as isize + dy as isize;
+
+            if nx < 0 || ny < 0 || nx as usize >= size || ny as usize >= size {
+                continue;
+            }
+
+            let next = (nx as usize, ny as usize);
+            let new_cost = cost_so_far[&current] + step_cost(grid.get(next.0, next.1), rng);
+
+            if !cost_so_far.contains_key(&next) || new_cost < cost_so_far[&next] {
+                cost_so_far.insert(next, new_cost);
+                frontier.push(Reverse((new_cost + manhattan_dist(next, end), next)));
+                came_from.insert(next, current);
+            }
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut current = end;
+
+    while current != start {
+        path.push(current);
+        match came_from.get(&current) {
+            Some(&prev) => current = prev,
+            None => return Vec::new()
+        }
+    }
+
+    path.push(start);
+    path.reverse();
+    path
+}
+
+fn carve_corridor(grid: &mut TileGrid, from: Point, to: Point, rng: &mut StdRng) {
+    let path = a_star_path(grid, from, to, rng);
+
+    for &(x, y) in &path {
+        if let TileType::Empty | TileType::Wall = grid.get(x, y) {
+            grid.set_tile(x, y, TileType::Corridor);
+        }
+    }
+}
+
+/// Places 3-4 rejection-sampled rectangular rooms.
+pub struct RandomRooms;
+
+impl MapFilter for RandomRooms {
+    fn apply(&self, grid: &mut TileGrid, data: &mut BuildData, rng: &mut StdRng) {
+        let room_number = rng.gen_range(3, 5);
+
+        for _ in 0..room_number {
+            let room = random_room(grid, &data.rooms, rng).unwrap();
+            room.tile(grid).unwrap();
+            data.rooms.push(room);
+        }
+    }
+}
+
+/// Places one room per leaf of a binary-space-partition split of the grid,
+/// guaranteeing non-overlapping, well-distributed rooms without rejection
+/// sampling.
+pub struct BspRooms;
+
+impl MapFilter for BspRooms {
+    fn apply(&self, grid: &mut TileGrid, data: &mut BuildData, rng: &mut StdRng) {
+        let size = grid.raw_data().len();
+        let leaves = bsp_leaves(size, rng);
+
+        for leaf in &leaves {
+            let room = room_in_leaf(leaf, rng);
+            room.tile(grid).unwrap();
+            data.rooms.push(room);
+        }
+    }
+}
+
+/// Carves an A* corridor from each room to its nearest neighbor, then
+/// chains every consecutive pair so the whole level stays connected
+/// regardless of which placement filter produced `data.rooms`.
+pub struct AStarCorridors;
+
+impl MapFilter for AStarCorridors {
+    fn apply(&self, grid: &mut TileGrid, data: &mut BuildData, rng: &mut StdRng) {
+        let centers: Vec<Point> = data.rooms.iter().map(|room| room.center).collect();
+
+        if centers.len() < 2 {
+            return;
+        }
+
+        for &center in &centers {
+            let distances = room_distances(center, &data.rooms);
+            let nearest = centers[distances[1].0];
+            carve_corridor(grid, center, nearest, rng);
+        }
+
+        for pair in centers.windows(2) {
+            carve_corridor(grid, pair[0], pair[1], rng);
+        }
+    }
+}
+
+/// Percent chance that a room wall touching the maze gets knocked open.
+const MAZE_ROOM_OPENING_CHANCE: u32 = 20;
+
+/// Runs a randomized recursive-backtracker from `start`, carving two-tile
+/// steps into unvisited `Empty` neighbors, leaving a one-tile rock border.
+fn carve_maze_from(grid: &mut TileGrid, visited: &mut [Vec<bool>], start: Point, size: usize, rng: &mut StdRng) {
+    let mut stack = vec![start];
+    visited[start.1][start.0] = true;
+    grid.set_tile(start.0, start.1, TileType::Corridor);
+
+    while let Some(&(x, y)) = stack.last() {
+        let mut candidates = Vec::new();
+
+        for &(dx, dy) in &[LEFT, RIGHT, UP, DOWN] {
+            let nx = x as isize + dx as isize * 2;
+            let ny = y as isize + dy as isize * 2;
+
+            if nx < 1 || ny < 1 || nx as usize >= size - 1 || ny as usize >= size - 1 {
+                continue;
+            }
+
+            let next = (nx as usize, ny as usize);
+            if !visited[next.1][next.0] && matches!(grid.get(next.0, next.1), TileType::Empty) {
+                candidates.push((next, (dx, dy)));
+            }
+        }
+
+        if candidates.is_empty() {
+            stack.pop();
+            continue;
+        }
+
+        let (next, (dx, dy)) = candidates[rng.gen_range(0, candidates.len())];
+        let between = ((x as isize + dx as isize) as usize, (y as isize + dy as isize) as usize);
+
+        grid.set_tile(between.0, between.1, TileType::Corridor);
+        grid.set_tile(next.0, next.1, TileType::Corridor);
+        visited[next.1][next.0] = true;
+        stack.push(next);
+    }
+}
+
+/// Knocks a few openings into room walls that touch a `Corridor` tile (maze
+/// or otherwise), so the maze merges into the level instead of staying
+/// sealed off.
+fn open_maze_into_rooms(grid: &mut TileGrid, rooms: &[Room], rng: &mut StdRng) {
+    let size = grid.raw_data().len();
+
+    for room in rooms {
+        let endx = room.start.0 + room.width;
+        let endy = room.start.1 + room.height;
+
+        let mut wall_tiles: Vec<Point> = (room.start.0..=endx)
+            .flat_map(|x| vec![(x, room.start.1), (x, endy)])
+            .collect();
+        wall_tiles.extend((room.start.1..=endy).flat_map(|y| vec![(room.start.0, y), (endx, y)]));
+
+        for (x, y) in wall_tiles {
+            if !matches!(grid.get(x, y), TileType::Wall) {
+                continue;
+            }
+
+            let touches_maze = [LEFT, RIGHT, UP, DOWN].iter().any(|&(dx, dy)| {
+                let nx = x as isize + dx as isize;
+                let ny = y as isize + dy as isize;
+
+                nx >= 0 && ny >= 0 && (nx as usize) < size && (ny as usize) < size
+                    && matches!(grid.get(nx as usize, ny as usize), TileType::Corridor)
+            });
+
+            if touches_maze && rng.gen_range(0, 100) < MAZE_ROOM_OPENING_CHANCE {
+                grid.set_tile(x, y, TileType::Corridor);
+            }
+        }
+    }
+}
+
+/// Runs a randomized recursive-backtracker maze over the grid's leftover
+/// `Empty` space, then knocks a few openings into adjacent rooms so the
+/// maze merges into the level instead of forming a sealed labyrinth.
+pub struct MazeFill;
+
+impl MapFilter for MazeFill {
+    fn apply(&self, grid: &mut TileGrid, data: &mut BuildData, rng: &mut StdRng) {
+        let size = grid.raw_data().len();
+        let mut visited = vec![vec![false; size]; size];
+
+        for y in (1..size.saturating_sub(1)).step_by(2) {
+            for x in (1..size.saturating_sub(1)).step_by(2) {
+                if !visited[y][x] && matches!(grid.get(x, y), TileType::Empty) {
+                    carve_maze_from(grid, &mut visited, (x, y), size, rng);
+                }
+            }
+        }
+
+        open_maze_into_rooms(grid, &data.rooms, rng);
+    }
+}
+
+/// Fills the grid with an organic cellular-automata cave instead of
+/// rectangular rooms.
+pub struct CaveFill {
+    wall_percent: u32,
+    smoothing_passes: usize
+}
+
+impl CaveFill {
+    pub fn new() -> CaveFill {
+        CaveFill {
+            wall_percent: 45,
+            smoothing_passes: 4
+        }
+    }
+}
+
+impl Default for CaveFill {
+    fn default() -> CaveFill {
+        CaveFill::new()
+    }
+}
+
+impl MapFilter for CaveFill {
+    fn apply(&self, grid: &mut TileGrid, _data: &mut BuildData, rng: &mut StdRng) {
+        grid.fill_random(self.wall_percent, rng);
+
+        for _ in 0..self.smoothing_passes {
+            grid.smooth();
+        }
+    }
+}
+
+/// Flood-fills from a spawn point (the first room's center, or the first
+/// floor tile if there are no rooms yet) and walls off every floor/corridor
+/// tile that wasn't reached, so no player can spawn in a sealed-off pocket.
+pub struct CullUnreachable;
+
+impl MapFilter for CullUnreachable {
+    fn apply(&self, grid: &mut TileGrid, data: &mut BuildData, _rng: &mut StdRng) {
+        let spawn = data.spawn
+            .or_else(|| data.rooms.first().map(|room| room.center))
+            .or_else(|| grid.first_floor())
+            .unwrap_or((0, 0));
+
+        data.spawn = Some(spawn);
+        grid.cull_unreachable(spawn);
+    }
+}
+
+/// Builds a level by running a chain of `MapFilter` stages over a shared
+/// `TileGrid`, e.g. `World::new(128).with(BspRooms).with(AStarCorridors).with(CullUnreachable).build()`.
+pub struct World {
+    size: usize,
+    rng: StdRng,
+    filters: Vec<Box<dyn MapFilter>>,
+    record_snapshots: bool,
+    snapshots: Vec<TileGrid>
+}
+
+impl World {
+    pub fn new(size: usize) -> World {
+        World {
+            size,
+            rng: StdRng::from_entropy(),
+            filters: Vec::new(),
+            record_snapshots: false,
+            snapshots: Vec::new()
+        }
+    }
+
+    /// Same as `new`, but seeds the internal RNG from `seed` so that worlds
+    /// created with the same seed and filters produce an identical tile
+    /// layout on their first `build`. The RNG is not reset between calls, so
+    /// a second `build` on the same world gives a different layout.
+    pub fn new_seeded(size: usize, seed: u64) -> World {
+        World {
+            size,
+            rng: StdRng::seed_from_u64(seed),
+            filters: Vec::new(),
+            record_snapshots: false,
+            snapshots: Vec::new()
+        }
+    }
+
+    /// Queues a generation stage. Stages run in the order they're added.
+    pub fn with<F: MapFilter + 'static>(mut self, filter: F) -> World {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Enables snapshot recording: after `build`, `snapshot_history` returns
+    /// a clone of the grid taken after every filter ran. Off by default so
+    /// ordinary generation pays no extra cloning cost.
+    pub fn record_snapshots(mut self) -> World {
+        self.record_snapshots = true;
+        self
+    }
+
+    /// The grid as it looked after each filter stage of the last `build`
+    /// call, in order. Empty unless `record_snapshots` was enabled.
+    pub fn snapshot_history(&self) -> &[TileGrid] {
+        &self.snapshots
+    }
+
+    /// Runs every queued filter over a blank grid and returns the result
+    /// together with the rooms placed and the spawn point chosen.
+    pub fn build(&mut self) -> (TileGrid, BuildData) {
+        let mut grid = TileGrid::new(self.size);
+        let mut data = BuildData::new();
+        self.snapshots.clear();
+
+        for filter in &self.filters {
+            filter.apply(&mut grid, &mut data, &mut self.rng);
+
+            if self.record_snapshots {
+                self.snapshots.push(grid.clone());
+            }
+        }
+
+        if data.spawn.is_none() {
+            data.spawn = data.rooms.first().map(|room| room.center).or_else(|| grid.first_floor());
+        }
+
+        (grid, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_world_with_random_rooms() {
+        let mut world = World::new(128)
+            .with(RandomRooms)
+            .with(AStarCorridors)
+            .with(CullUnreachable);
+
+        world.build();
+    }
+
+    #[test]
+    fn test_builds_cave_world() {
+        let mut world = World::new_seeded(64, 3)
+            .with(CaveFill::new())
+            .with(CullUnreachable);
+
+        let (grid, _) = world.build();
+        let flat: Vec<&TileType> = grid.raw_data().iter().flatten().collect();
+
+        assert!(flat.iter().any(|tile| **tile == TileType::Floor));
+        assert!(flat.iter().any(|tile| **tile == TileType::Wall));
+    }
+
+    #[test]
+    fn test_seeded_build_is_reproducible() {
+        let pipeline = || World::new_seeded(64, 42)
+            .with(RandomRooms)
+            .with(AStarCorridors)
+            .with(CullUnreachable);
+
+        let (grid_a, data_a) = pipeline().build();
+        let (grid_b, data_b) = pipeline().build();
+
+        assert_eq!(grid_a.raw_data(), grid_b.raw_data());
+        assert_eq!(data_a.spawn, data_b.spawn);
+    }
+
+    #[test]
+    fn test_build_culls_unreachable_floor() {
+        let mut world = World::new_seeded(48, 9)
+            .with(RandomRooms)
+            .with(AStarCorridors)
+            .with(CullUnreachable);
+
+        let (grid, data) = world.build();
+        let spawn = data.spawn.unwrap();
+        let tiles = grid.raw_data();
+        let size = tiles.len();
+
+        let mut reached = vec![vec![false; size]; size];
+        let mut frontier = std::collections::VecDeque::new();
+        reached[spawn.1][spawn.0] = true;
+        frontier.push_back(spawn);
+
+        while let Some((x, y)) = frontier.pop_front() {
+            for &(dx, dy) in &[(-1i32, 0), (1, 0), (0, -1), (0, 1)] {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+
+                if nx < 0 || ny < 0 || nx as usize >= size || ny as usize >= size {
+                    continue;
+                }
+
+                let next = (nx as usize, ny as usize);
+                if reached[next.1][next.0] {
+                    continue;
+                }
+
+                if matches!(tiles[next.1][next.0], TileType::Floor | TileType::Corridor) {
+                    reached[next.1][next.0] = true;
+                    frontier.push_back(next);
+                }
+            }
+        }
+
+        for y in 0..size {
+            for x in 0..size {
+                if matches!(tiles[y][x], TileType::Floor | TileType::Corridor) {
+                    assert!(reached[y][x], "tile ({}, {}) should be reachable from spawn", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bsp_rooms_do_not_overlap() {
+        let mut world = World::new_seeded(128, 11).with(BspRooms);
+        let (_, data) = world.build();
+
+        for (i, a) in data.rooms.iter().enumerate() {
+            for b in data.rooms.iter().skip(i + 1) {
+                let separate = a.start.0 + a.width <= b.start.0
+                    || b.start.0 + b.width <= a.start.0
+                    || a.start.1 + a.height <= b.start.1
+                    || b.start.1 + b.height <= a.start.1;
+
+                assert!(separate);
+            }
+        }
+    }
+
+    #[test]
+    fn test_astar_corridors_do_not_panic_with_a_single_bsp_room() {
+        for seed in 0..200 {
+            let mut world = World::new_seeded(10, seed)
+                .with(BspRooms)
+                .with(AStarCorridors);
+
+            world.build();
+        }
+    }
+
+    #[test]
+    fn test_astar_corridors_carve_corridor_tiles() {
+        let mut world = World::new_seeded(64, 7)
+            .with(RandomRooms)
+            .with(AStarCorridors);
+
+        let (grid, data) = world.build();
+        let has_corridor = grid.raw_data().iter().flatten().any(|tile| *tile == TileType::Corridor);
+
+        assert!(!data.rooms.is_empty());
+        assert!(has_corridor);
+    }
+
+    #[test]
+    fn test_maze_fill_carves_leftover_space() {
+        let count_corridors = |grid: &TileGrid| {
+            grid.raw_data().iter().flatten().filter(|tile| **tile == TileType::Corridor).count()
+        };
+
+        let mut without_maze = World::new_seeded(64, 7)
+            .with(RandomRooms)
+            .with(AStarCorridors);
+        let (grid_without_maze, _) = without_maze.build();
+
+        let mut with_maze = World::new_seeded(64, 7)
+            .with(RandomRooms)
+            .with(AStarCorridors)
+            .with(MazeFill);
+        let (grid_with_maze, _) = with_maze.build();
+
+        assert!(count_corridors(&grid_with_maze) > count_corridors(&grid_without_maze));
+    }
+
+    #[test]
+    fn test_snapshot_history_is_empty_unless_enabled() {
+        let mut world = World::new_seeded(64, 5)
+            .with(RandomRooms)
+            .with(AStarCorridors);
+
+        world.build();
+
+        assert!(world.snapshot_history().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_history_records_one_grid_per_filter() {
+        let mut world = World::new_seeded(64, 5)
+            .with(RandomRooms)
+            .with(AStarCorridors)
+            .with(CullUnreachable)
+            .record_snapshots();
+
+        let (grid, _) = world.build();
+        let history = world.snapshot_history();
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.last().unwrap().raw_data(), grid.raw_data());
+    }
+}